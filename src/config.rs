@@ -1,6 +1,7 @@
 use std::collections::HashMap;
 use std::fmt::{self, Display, Formatter};
 use std::net::{IpAddr, SocketAddr};
+use std::num::NonZeroUsize;
 use std::time::Duration;
 
 use serde::{de, Deserialize};
@@ -14,19 +15,103 @@ pub struct Config {
     #[serde(default = "default_timeout")]
     #[serde(deserialize_with = "deserialize_timeout")]
     pub timeout: Duration,
+    #[serde(default = "default_shutdown_timeout")]
+    #[serde(deserialize_with = "deserialize_timeout")]
+    pub shutdown_timeout: Duration,
+    #[serde(default)]
+    pub tls: Option<Tls>,
+    #[serde(default)]
+    pub http: Http,
+    #[serde(default)]
+    pub max_concurrency: Option<NonZeroUsize>,
+    #[serde(default)]
+    pub overload_policy: OverloadPolicy,
+    /// If set, `GET` requests to this path are served as a live `text/event-stream` of hook
+    /// execution events (start, stdout/stderr lines, and exit) instead of being routed to a hook.
+    #[serde(default)]
+    pub status_path: Option<Box<str>>,
     #[serde(deserialize_with = "deserialize_hook")]
     pub hook: HashMap<Box<str>, Hook>,
 }
 
+/// What to do with a hook invocation that arrives once `max_concurrency` running hooks are
+/// already in flight.
+#[derive(Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OverloadPolicy {
+    /// Wait for a slot to free up before starting the hook, delaying the response.
+    Queue,
+    /// Respond with `503 Service Unavailable` immediately instead of starting the hook.
+    Reject,
+}
+
+impl Default for OverloadPolicy {
+    fn default() -> Self {
+        OverloadPolicy::Queue
+    }
+}
+
 fn default_timeout() -> Duration {
     Duration::from_secs(60)
 }
 
+fn default_shutdown_timeout() -> Duration {
+    Duration::from_secs(30)
+}
+
+#[non_exhaustive]
+#[derive(Deserialize)]
+pub struct Tls {
+    pub cert: Box<str>,
+    pub key: Box<str>,
+}
+
+#[non_exhaustive]
+#[derive(Default, Deserialize)]
+pub struct Http {
+    #[serde(default)]
+    pub version: HttpVersion,
+}
+
+#[derive(Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HttpVersion {
+    Auto,
+    #[serde(rename = "1")]
+    Http1,
+    #[serde(rename = "2")]
+    Http2,
+}
+
+impl Default for HttpVersion {
+    fn default() -> Self {
+        HttpVersion::Auto
+    }
+}
+
 #[non_exhaustive]
 pub struct Hook {
     pub program: Box<str>,
     pub args: Option<Box<[Box<str>]>>,
     pub secret: Option<Box<str>>,
+    /// When a `secret` is set, stream the body into the hook's stdin as it arrives instead of
+    /// buffering it fully and verifying the signature first. This bounds memory for large
+    /// payloads, but means unverified bytes reach the program before verification completes; the
+    /// child is killed immediately on a signature mismatch. Ignored when no `secret` is set,
+    /// since unsigned bodies are always streamed.
+    pub stream: bool,
+    /// If set, only a request signed with one of these algorithms is accepted; a request signed
+    /// with any other is rejected with `406 Not Acceptable` without ever reaching the hook. Unset
+    /// accepts whichever of `X-Hub-Signature-256` or `X-Hub-Signature` the sender provided.
+    pub algorithms: Option<Box<[Algorithm]>>,
+}
+
+/// A digest algorithm used to sign a hook's request body.
+#[derive(Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Algorithm {
+    Sha1,
+    Sha256,
 }
 
 pub(crate) struct DisplayHookCommand<'a>(pub &'a Hook);
@@ -61,12 +146,18 @@ where
                 args: Option<Box<[Box<str>]>>,
                 #[serde(default)]
                 secret: Option<Box<str>>,
+                #[serde(default)]
+                stream: bool,
+                #[serde(default)]
+                algorithms: Option<Box<[Algorithm]>>,
             }
             while let Some(p) = a.next_element::<HookPrototype>()? {
                 let hook = Hook {
                     program: p.program,
                     args: p.args,
                     secret: p.secret,
+                    stream: p.stream,
+                    algorithms: p.algorithms,
                 };
                 ret.insert(p.location, hook);
             }
@@ -2,33 +2,42 @@ pub mod config;
 
 mod service;
 mod socket;
-mod util;
 
 pub use crate::config::Config;
 
 use std::convert::TryInto;
+use std::fs;
 use std::future::Future;
 use std::io;
+use std::io::BufReader;
 use std::pin::Pin;
 use std::sync::Arc;
 use std::task::{Context, Poll};
 
+use anyhow::Context as _;
 use hyper::server::conn::Http;
 use listenfd::ListenFd;
 use tokio::net::TcpListener;
+use tokio::sync::watch;
+use tokio_rustls::rustls;
+use tokio_rustls::TlsAcceptor;
 
+use crate::config::HttpVersion;
 use crate::service::Service;
-use crate::socket::Listener;
+use crate::socket::{Listener, Stream};
 
 pub struct Server {
     incoming: Listener,
     http: Http,
+    http_version: HttpVersion,
     service: Arc<Service>,
+    shutdown_tx: watch::Sender<bool>,
+    shutdown_rx: watch::Receiver<bool>,
 }
 
 impl Server {
     pub async fn new(config: Config) -> anyhow::Result<Self> {
-        let incoming = if let Some(addr) = config.bind {
+        let mut incoming = if let Some(addr) = config.bind {
             Listener::Tcp(TcpListener::bind(addr).await?)
         } else if let Some(l) = listen_fd()? {
             l
@@ -36,12 +45,44 @@ impl Server {
             anyhow::bail!("Either `bind` in config or `$LISTEN_FD` must be provided");
         };
 
+        let http_version = config.http.version;
+
+        if let Some(tls) = config.tls.as_ref() {
+            let acceptor = load_tls_acceptor(tls, http_version)?;
+            incoming = Listener::tls(incoming, acceptor);
+        }
+
+        let mut http = Http::new();
+        match http_version {
+            HttpVersion::Http1 => {
+                http.http1_only(true);
+            }
+            HttpVersion::Http2 => {
+                http.http2_only(true);
+            }
+            HttpVersion::Auto => {}
+        }
+
+        let (shutdown_tx, shutdown_rx) = watch::channel(false);
+
         Ok(Server {
             incoming,
-            http: Http::new(),
-            service: Arc::new(Service::new(config)),
+            http,
+            http_version,
+            service: Arc::new(Service::new(config, shutdown_rx.clone())),
+            shutdown_tx,
+            shutdown_rx,
         })
     }
+
+    /// Stops accepting new connections, asks outstanding connections to finish their in-flight
+    /// requests via HTTP's own graceful-shutdown mechanism, and waits for every hook this server
+    /// has spawned to exit, up to `shutdown_timeout` (killing stragglers past the deadline).
+    pub async fn shutdown(self) -> anyhow::Result<()> {
+        let _ = self.shutdown_tx.send(true);
+        self.service.wait_for_hooks().await;
+        Ok(())
+    }
 }
 
 impl Future for Server {
@@ -49,13 +90,88 @@ impl Future for Server {
 
     fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
         while let Poll::Ready(io) = self.incoming.poll_accept(cx)? {
-            let service = util::DerefService(self.service.clone());
-            tokio::spawn(self.http.serve_connection(io, service));
+            let service = self.service.clone();
+            let http = negotiate_http(&self.http, self.http_version, &io);
+            let mut shutdown_rx = self.shutdown_rx.clone();
+            tokio::spawn(async move {
+                let mut conn = http.serve_connection(io, service);
+                tokio::select! {
+                    result = &mut conn => {
+                        if let Err(e) = result {
+                            log::warn!("Error serving connection: {:?}", e);
+                        }
+                    }
+                    _ = shutdown_rx.changed() => {
+                        Pin::new(&mut conn).graceful_shutdown();
+                        if let Err(e) = conn.await {
+                            log::warn!("Error serving connection during shutdown: {:?}", e);
+                        }
+                    }
+                }
+            });
         }
         Poll::Pending
     }
 }
 
+/// For TLS connections in `auto` mode, picks `http1_only`/`http2_only` based on the protocol
+/// negotiated via ALPN, since the handshake has already happened by the time we get here.
+/// Plaintext connections keep whatever mode was configured up front, relying on `hyper`'s
+/// support for HTTP/2 prior knowledge (h2c) when neither flag is forced.
+fn negotiate_http(http: &Http, version: HttpVersion, io: &Stream) -> Http {
+    if !matches!(version, HttpVersion::Auto) {
+        return http.clone();
+    }
+    let mut http = http.clone();
+    match io.alpn_protocol() {
+        Some(b"h2") => {
+            http.http2_only(true);
+        }
+        Some(_) => {
+            http.http1_only(true);
+        }
+        None => {}
+    }
+    http
+}
+
+fn load_tls_acceptor(tls: &config::Tls, http_version: HttpVersion) -> anyhow::Result<TlsAcceptor> {
+    let certs = load_certs(&tls.cert)?;
+    let key = load_key(&tls.key)?;
+    let mut config = rustls::ServerConfig::builder()
+        .with_safe_defaults()
+        .with_no_client_auth()
+        .with_single_cert(certs, key)
+        .context("Failed to build the TLS server config")?;
+    // Only offer the protocols `negotiate_http` is actually prepared to act on: when a version
+    // is forced up front, offering the other protocol too would let ALPN pick it while we still
+    // hand the connection to an `http1_only`/`http2_only` builder, breaking the connection.
+    config.alpn_protocols = match http_version {
+        HttpVersion::Http1 => vec![b"http/1.1".to_vec()],
+        HttpVersion::Http2 => vec![b"h2".to_vec()],
+        HttpVersion::Auto => vec![b"h2".to_vec(), b"http/1.1".to_vec()],
+    };
+    Ok(TlsAcceptor::from(Arc::new(config)))
+}
+
+fn load_certs(path: &str) -> anyhow::Result<Vec<rustls::Certificate>> {
+    let file = fs::File::open(path).with_context(|| format!("Failed to open `{}`", path))?;
+    let certs = rustls_pemfile::certs(&mut BufReader::new(file))
+        .with_context(|| format!("Failed to parse certificate(s) in `{}`", path))?;
+    Ok(certs.into_iter().map(rustls::Certificate).collect())
+}
+
+fn load_key(path: &str) -> anyhow::Result<rustls::PrivateKey> {
+    let file = fs::File::open(path).with_context(|| format!("Failed to open `{}`", path))?;
+    let keys = rustls_pemfile::pkcs8_private_keys(&mut BufReader::new(file))
+        .with_context(|| format!("Failed to parse the private key in `{}`", path))?;
+    let key = keys
+        .into_iter()
+        .next()
+        .with_context(|| format!("No private key found in `{}`", path))?;
+    Ok(rustls::PrivateKey(key))
+}
+
 fn listen_fd() -> io::Result<Option<Listener>> {
     let mut fds = ListenFd::from_env();
     if let Some(l) = fds.take_tcp_listener(0).ok().flatten() {
@@ -11,21 +11,22 @@ async fn main() -> anyhow::Result<()> {
     let config = fs::read("webhook.toml").context("Failed to open `webhook.toml`")?;
     let config: Config = toml::from_slice(&config).context("Failed to parse `webhook.toml`")?;
 
-    let server = Server::new(config)
+    let mut server = Server::new(config)
         .await
         .context("Failed to start the server")?;
-    let ctrl_c = ctrl_c();
 
     log::info!("Starting the server");
 
     tokio::select! {
-        result = server => {
+        result = &mut server => {
             result?;
             log::info!("The server has exited");
         }
-        result = ctrl_c => {
+        result = ctrl_c() => {
             result?;
-            log::info!("Received SIGINT, exiting");
+            log::info!("Received SIGINT, shutting down gracefully");
+            server.shutdown().await?;
+            log::info!("The server has exited");
         }
     }
 
@@ -1,41 +1,167 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
 use std::fmt::Debug;
 use std::future;
 use std::io;
+use std::pin::Pin;
 use std::process::Stdio;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
 use std::task::{Context, Poll};
 use std::time::Duration;
 
+use bytes::Buf;
 use futures_util::future::Either;
+use futures_util::stream::{self, StreamExt};
 use hmac::digest::generic_array::typenum::Unsigned;
 use hmac::digest::FixedOutput;
 use hmac::{Hmac, Mac, NewMac};
 use http::header::HeaderName;
-use http::StatusCode;
+use http::{Method, StatusCode};
 use http_body::Body;
-use http_body::Empty;
 use sha1::Sha1;
-use tokio::io::AsyncWriteExt;
+use sha2::Sha256;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWriteExt, BufReader};
 use tokio::process::Command;
+use tokio::sync::{broadcast, watch, Semaphore};
+use tokio_util::task::TaskTracker;
 
-use crate::config::{Config, DisplayHookCommand, Hook};
+use crate::config::{self, Algorithm, Config, DisplayHookCommand, Hook};
 
 pub struct Service {
     hooks: HashMap<Box<str>, Hook>,
     timeout: Duration,
+    shutdown_timeout: Duration,
+    shutdown_rx: watch::Receiver<bool>,
+    tasks: TaskTracker,
+    semaphore: Option<Arc<Semaphore>>,
+    overload_policy: config::OverloadPolicy,
+    status_path: Option<Box<str>>,
+    events: EventBus,
+    next_execution_id: AtomicU64,
 }
 
 const X_HUB_SIGNATURE: &str = "x-hub-signature";
+const X_HUB_SIGNATURE_256: &str = "x-hub-signature-256";
+const LAST_EVENT_ID: &str = "last-event-id";
+
+/// How often to send an SSE keep-alive comment to status endpoint subscribers.
+const KEEP_ALIVE_INTERVAL: Duration = Duration::from_secs(15);
+
+/// Capacity of the broadcast channel publishing hook execution events, and the number of most
+/// recent events kept in [`EventBus`]'s replay history for reconnecting subscribers; a lagging
+/// subscriber simply misses the oldest ones rather than stalling everyone else.
+const EVENTS_CAPACITY: usize = 1024;
+
+/// Publishes hook execution events to subscribers of the `status_path` endpoint.
+///
+/// Besides the plain `broadcast` channel, this keeps a bounded history of recently published
+/// events tagged with a monotonically increasing sequence number, distinct from the
+/// hook-execution id carried in the event itself (several events share one execution id, but
+/// never a sequence number). A reconnecting `EventSource` client can send back the last
+/// sequence number it saw via `Last-Event-ID` and receive exactly the events it missed, instead
+/// of silently losing everything published while it was disconnected.
+#[derive(Clone)]
+struct EventBus {
+    tx: broadcast::Sender<(u64, Event)>,
+    history: Arc<Mutex<VecDeque<(u64, Event)>>>,
+    next_seq: Arc<AtomicU64>,
+}
+
+impl EventBus {
+    fn new() -> Self {
+        let (tx, _) = broadcast::channel(EVENTS_CAPACITY);
+        EventBus {
+            tx,
+            history: Arc::new(Mutex::new(VecDeque::with_capacity(EVENTS_CAPACITY))),
+            next_seq: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    fn publish(&self, event: Event) {
+        // Number, record and send under the history lock, so that events reach the history and
+        // the channel in sequence order, which the `last_seq` dedup in `serve_status` relies on.
+        let mut history = self.history.lock().unwrap();
+        let seq = self.next_seq.fetch_add(1, Ordering::Relaxed);
+        if history.len() == EVENTS_CAPACITY {
+            history.pop_front();
+        }
+        history.push_back((seq, event.clone()));
+        let _ = self.tx.send((seq, event));
+    }
+
+    fn subscribe(&self) -> broadcast::Receiver<(u64, Event)> {
+        self.tx.subscribe()
+    }
+
+    /// Snapshots the events published after sequence number `after`, or nothing if `after` is
+    /// `None` (a first-time subscriber has nothing to resume, and shouldn't be replayed the
+    /// whole history).
+    fn history_since(&self, after: Option<u64>) -> Vec<(u64, Event)> {
+        let after = match after {
+            Some(after) => after,
+            None => return Vec::new(),
+        };
+        self.history
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(seq, _)| *seq > after)
+            .cloned()
+            .collect()
+    }
+}
+
+#[derive(Clone)]
+enum Event {
+    Started {
+        id: u64,
+        hook: Box<str>,
+    },
+    Stdout {
+        id: u64,
+        hook: Box<str>,
+        line: Box<str>,
+    },
+    Stderr {
+        id: u64,
+        hook: Box<str>,
+        line: Box<str>,
+    },
+    Exited {
+        id: u64,
+        hook: Box<str>,
+        code: Option<i32>,
+        timed_out: bool,
+    },
+}
 
 impl Service {
-    pub fn new(config: Config) -> Self {
+    pub fn new(config: Config, shutdown_rx: watch::Receiver<bool>) -> Self {
+        let events = EventBus::new();
         Service {
             hooks: config.hook,
             timeout: config.timeout,
+            shutdown_timeout: config.shutdown_timeout,
+            shutdown_rx,
+            tasks: TaskTracker::new(),
+            semaphore: config
+                .max_concurrency
+                .map(|n| Arc::new(Semaphore::new(n.get()))),
+            overload_policy: config.overload_policy,
+            status_path: config.status_path,
+            events,
+            next_execution_id: AtomicU64::new(0),
         }
     }
 
-    fn call<B>(&self, req: http::Request<B>) -> http::Response<Empty<&'static [u8]>>
+    /// Closes the hook task tracker and waits for every task spawned on it to finish. Closing
+    /// does not refuse later spawns: a hook started meanwhile is tracked and waited for too.
+    pub async fn wait_for_hooks(&self) {
+        self.tasks.close();
+        self.tasks.wait().await;
+    }
+
+    async fn call<B>(&self, req: http::Request<B>) -> http::Response<hyper::Body>
     where
         B: Body + Send + 'static,
         B::Data: Send,
@@ -43,51 +169,125 @@ impl Service {
     {
         let res = http::Response::builder();
 
+        if req.method() == Method::GET && self.status_path.as_deref() == Some(req.uri().path()) {
+            return self.serve_status(&req);
+        }
+
         let hook = if let Some(hook) = self.hooks.get(req.uri().path()) {
             hook
         } else {
             return res
                 .status(StatusCode::NOT_FOUND)
-                .body(Empty::new())
+                .body(hyper::Body::empty())
                 .unwrap();
         };
+        let location: Box<str> = Box::from(req.uri().path());
 
         let verifier = if let Some(secret) = hook.secret.as_ref() {
-            let mac = Hmac::<Sha1>::new_varkey(secret.as_bytes()).unwrap();
-            let signature =
-                if let Some(v) = req.headers().get(HeaderName::from_static(X_HUB_SIGNATURE)) {
-                    match parse_signature_header(v.as_bytes()) {
-                        Ok(s) => s,
-                        Err(SignatureParseError::Malformed) => {
-                            return res
-                                .status(StatusCode::BAD_REQUEST)
-                                .body(Empty::new())
-                                .unwrap()
-                        }
-                        Err(SignatureParseError::UnknownAlgorithm) => {
-                            return res
-                                .status(StatusCode::NOT_ACCEPTABLE)
-                                .body(Empty::new())
-                                .unwrap()
-                        }
+            // Senders that set a `X-Hub-Signature-256` header conventionally send the legacy
+            // `X-Hub-Signature` alongside it for backwards compatibility; try them in order of
+            // preference and use the first one this hook's `algorithms` allow-list accepts,
+            // rather than rejecting outright just because the most-preferred header didn't match.
+            let headers = [X_HUB_SIGNATURE_256, X_HUB_SIGNATURE];
+            // The most severe problem seen so far among the headers tried, in case none of them
+            // yields a usable signature: a malformed header outranks an unacceptable algorithm,
+            // which in turn outranks simply finding nothing, so the response reflects the most
+            // specific thing that actually went wrong rather than always falling back to 401.
+            let mut fallback_status: Option<StatusCode> = None;
+            let mut note_fallback = |status: StatusCode, fallback_status: &mut Option<StatusCode>| {
+                fn rank(status: StatusCode) -> u8 {
+                    match status {
+                        StatusCode::BAD_REQUEST => 2,
+                        StatusCode::NOT_ACCEPTABLE => 1,
+                        _ => 0,
                     }
+                }
+                if fallback_status.map_or(true, |cur| rank(status) > rank(cur)) {
+                    *fallback_status = Some(status);
+                }
+            };
+            let mut signature = None;
+            for name in headers {
+                let v = if let Some(v) = req.headers().get(HeaderName::from_static(name)) {
+                    v
                 } else {
-                    return res
-                        .status(StatusCode::UNAUTHORIZED)
-                        .body(Empty::new())
-                        .unwrap();
+                    continue;
+                };
+                let s = match parse_signature_header(v.as_bytes()) {
+                    Ok(s) => s,
+                    Err(SignatureParseError::Malformed) => {
+                        note_fallback(StatusCode::BAD_REQUEST, &mut fallback_status);
+                        continue;
+                    }
+                    Err(SignatureParseError::UnknownAlgorithm) => {
+                        note_fallback(StatusCode::NOT_ACCEPTABLE, &mut fallback_status);
+                        continue;
+                    }
                 };
+                match hook.algorithms.as_deref() {
+                    Some(allowed) if !allowed.contains(&s.algorithm()) => {
+                        note_fallback(StatusCode::NOT_ACCEPTABLE, &mut fallback_status);
+                        continue;
+                    }
+                    _ => {
+                        signature = Some(s);
+                        break;
+                    }
+                }
+            }
+            let signature = if let Some(signature) = signature {
+                signature
+            } else {
+                return res
+                    .status(fallback_status.unwrap_or(StatusCode::UNAUTHORIZED))
+                    .body(hyper::Body::empty())
+                    .unwrap();
+            };
+            let mac = match signature {
+                Signature::Sha1(_) => {
+                    Verifier::Sha1(Hmac::<Sha1>::new_varkey(secret.as_bytes()).unwrap())
+                }
+                Signature::Sha256(_) => {
+                    Verifier::Sha256(Hmac::<Sha256>::new_varkey(secret.as_bytes()).unwrap())
+                }
+            };
             Some((mac, signature))
         } else {
             None
         };
 
+        let permit = if let Some(semaphore) = self.semaphore.as_ref() {
+            match self.overload_policy {
+                config::OverloadPolicy::Reject => match Arc::clone(semaphore).try_acquire_owned()
+                {
+                    Ok(permit) => Some(permit),
+                    Err(_) => {
+                        return res
+                            .status(StatusCode::SERVICE_UNAVAILABLE)
+                            .body(hyper::Body::empty())
+                            .unwrap();
+                    }
+                },
+                config::OverloadPolicy::Queue => {
+                    match Arc::clone(semaphore).acquire_owned().await {
+                        Ok(permit) => Some(permit),
+                        Err(_) => unreachable!("the semaphore is never closed"),
+                    }
+                }
+            }
+        } else {
+            None
+        };
+
         let body = req.into_body();
+        let stream = hook.stream;
 
         log::info!("Executing a hook: {}", DisplayHookCommand(hook));
 
         let mut cmd = Command::new(&*hook.program);
         cmd.stdin(Stdio::piped());
+        cmd.stdout(Stdio::piped());
+        cmd.stderr(Stdio::piped());
         for arg in hook.args.as_deref().into_iter().flatten() {
             cmd.arg(&**arg);
         }
@@ -101,7 +301,7 @@ impl Service {
                 }
                 return res
                     .status(StatusCode::INTERNAL_SERVER_ERROR)
-                    .body(Empty::new())
+                    .body(hyper::Body::empty())
                     .unwrap();
             }
         };
@@ -112,36 +312,126 @@ impl Service {
             log::error!("Failed to open stdin of child");
             return res
                 .status(StatusCode::INTERNAL_SERVER_ERROR)
-                .body(Empty::new())
+                .body(hyper::Body::empty())
                 .unwrap();
         };
 
+        let execution_id = self.next_execution_id.fetch_add(1, Ordering::Relaxed);
+        let events = self.events.clone();
+        events.publish(Event::Started {
+            id: execution_id,
+            hook: location.clone(),
+        });
+        if let Some(stdout) = child.stdout.take() {
+            let events = events.clone();
+            let location = location.clone();
+            self.tasks
+                .spawn(forward_output(stdout, events, location, execution_id, false));
+        }
+        if let Some(stderr) = child.stderr.take() {
+            let events = events.clone();
+            let location = location.clone();
+            self.tasks
+                .spawn(forward_output(stderr, events, location, execution_id, true));
+        }
+
         let timeout = self.timeout;
-        tokio::spawn(async move {
-            let body = match hyper::body::to_bytes(body).await {
-                Ok(body) => body,
-                Err(e) => {
-                    log::error!("Failed to read request body: {:?}", e);
-                    return;
-                }
+        let shutdown_timeout = self.shutdown_timeout;
+        let mut shutdown_rx = self.shutdown_rx.clone();
+        self.tasks.spawn(async move {
+            let _permit = permit;
+            let emit_exited = |code: Option<i32>, timed_out: bool| {
+                events.publish(Event::Exited {
+                    id: execution_id,
+                    hook: location.clone(),
+                    code,
+                    timed_out,
+                });
             };
             if let Some((mut mac, signature)) = verifier {
-                mac.update(&body);
-                let code = mac.finalize().into_bytes();
-                if *code != signature {
-                    log::warn!("Signature mismatch");
-                    return;
+                if stream {
+                    // The secret is verified only after the whole body has been written, so a
+                    // hostile or buggy sender can make the hook observe unverified bytes before
+                    // the mismatch is caught; the child is killed immediately once it is.
+                    tokio::pin!(body);
+                    loop {
+                        match future::poll_fn(|cx| body.as_mut().poll_data(cx)).await {
+                            Some(Ok(mut chunk)) => {
+                                let chunk = chunk.copy_to_bytes(chunk.remaining());
+                                mac.update(&chunk);
+                                if let Err(e) = stdin.write_all(&chunk).await {
+                                    if e.kind() != io::ErrorKind::BrokenPipe {
+                                        log::error!("Failed to write to the pipe: {:?}", e);
+                                        emit_exited(None, false);
+                                        return;
+                                    }
+                                }
+                            }
+                            Some(Err(e)) => {
+                                log::error!("Failed to read request body: {:?}", e);
+                                emit_exited(None, false);
+                                return;
+                            }
+                            None => break,
+                        }
+                    }
+                    if !mac.verify(&signature) {
+                        log::warn!("Signature mismatch");
+                        let _ = child.start_kill();
+                        emit_exited(None, false);
+                        return;
+                    }
+                } else {
+                    let body = match hyper::body::to_bytes(body).await {
+                        Ok(body) => body,
+                        Err(e) => {
+                            log::error!("Failed to read request body: {:?}", e);
+                            emit_exited(None, false);
+                            return;
+                        }
+                    };
+                    mac.update(&body);
+                    if !mac.verify(&signature) {
+                        log::warn!("Signature mismatch");
+                        let _ = child.start_kill();
+                        emit_exited(None, false);
+                        return;
+                    }
+                    if let Err(e) = stdin.write_all(&body).await {
+                        if e.kind() != io::ErrorKind::BrokenPipe {
+                            log::error!("Failed to write to the pipe: {:?}", e);
+                            emit_exited(None, false);
+                            return;
+                        }
+                    }
                 }
-            }
-            if let Err(e) = stdin.write_all(&body).await {
-                if e.kind() != io::ErrorKind::BrokenPipe {
-                    log::error!("Failed to write to the pipe: {:?}", e);
-                    return;
+            } else {
+                tokio::pin!(body);
+                loop {
+                    match future::poll_fn(|cx| body.as_mut().poll_data(cx)).await {
+                        Some(Ok(mut chunk)) => {
+                            let chunk = chunk.copy_to_bytes(chunk.remaining());
+                            if let Err(e) = stdin.write_all(&chunk).await {
+                                if e.kind() != io::ErrorKind::BrokenPipe {
+                                    log::error!("Failed to write to the pipe: {:?}", e);
+                                    emit_exited(None, false);
+                                    return;
+                                }
+                            }
+                        }
+                        Some(Err(e)) => {
+                            log::error!("Failed to read request body: {:?}", e);
+                            emit_exited(None, false);
+                            return;
+                        }
+                        None => break,
+                    }
                 }
             }
             if let Err(e) = stdin.shutdown().await {
                 if e.kind() != io::ErrorKind::BrokenPipe {
                     log::error!("Failed to close the pipe: {:?}", e);
+                    emit_exited(None, false);
                     return;
                 }
             }
@@ -155,47 +445,269 @@ impl Service {
             tokio::select! {
                 biased;
                 result = child.wait() => match result {
-                    Ok(status) => log::info!("Child exited. {}", status),
-                    Err(e) => log::error!("Error waiting for child: {:?}", e),
+                    Ok(status) => {
+                        log::info!("Child exited. {}", status);
+                        emit_exited(status.code(), false);
+                    }
+                    Err(e) => {
+                        log::error!("Error waiting for child: {:?}", e);
+                        emit_exited(None, false);
+                    }
                 },
                 _ = timeout => {
                     log::warn!("Timed out waiting for child");
                     let _ = child.start_kill();
+                    emit_exited(None, true);
+                }
+                _ = shutdown_rx.changed() => {
+                    tokio::select! {
+                        biased;
+                        result = child.wait() => match result {
+                            Ok(status) => {
+                                log::info!("Child exited. {}", status);
+                                emit_exited(status.code(), false);
+                            }
+                            Err(e) => {
+                                log::error!("Error waiting for child: {:?}", e);
+                                emit_exited(None, false);
+                            }
+                        },
+                        _ = tokio::time::sleep(shutdown_timeout) => {
+                            log::warn!("Shutdown grace period elapsed; killing child");
+                            let _ = child.start_kill();
+                            emit_exited(None, true);
+                        }
+                    }
+                }
+            }
+        });
+
+        res.body(hyper::Body::empty()).unwrap()
+    }
+
+    /// Serves an endless `text/event-stream` of hook execution events (start, output lines, and
+    /// exit) to subscribers of the `status_path` endpoint, with a periodic keep-alive comment to
+    /// keep idle connections from being reaped by intermediaries.
+    ///
+    /// A reconnecting `EventSource` client that sends `Last-Event-ID` is first replayed every
+    /// event published since then (from [`EventBus`]'s history), then joins the live stream.
+    fn serve_status<B>(&self, req: &http::Request<B>) -> http::Response<hyper::Body> {
+        let last_event_id = req
+            .headers()
+            .get(HeaderName::from_static(LAST_EVENT_ID))
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.trim().parse().ok());
+
+        // Subscribe before snapshotting the history, so that no event published in between is
+        // lost; this can make the snapshot and the live stream overlap at the boundary, which
+        // the `last_seq` check in the live loop below dedupes.
+        let rx = self.events.subscribe();
+        let replay = self.events.history_since(last_event_id);
+        let last_seq = replay.last().map(|&(seq, _)| seq).or(last_event_id);
+        let replay = stream::iter(
+            replay
+                .into_iter()
+                .map(|(seq, event)| -> io::Result<bytes::Bytes> {
+                    Ok(bytes::Bytes::from(format_event(seq, &event)))
+                }),
+        );
+
+        let live = stream::unfold((rx, last_seq), |(mut rx, mut last_seq)| async move {
+            loop {
+                tokio::select! {
+                    biased;
+                    result = rx.recv() => match result {
+                        Ok((seq, event)) => {
+                            if last_seq.map_or(false, |last_seq| seq <= last_seq) {
+                                continue;
+                            }
+                            last_seq = Some(seq);
+                            let chunk: io::Result<bytes::Bytes> =
+                                Ok(bytes::Bytes::from(format_event(seq, &event)));
+                            return Some((chunk, (rx, last_seq)));
+                        }
+                        Err(broadcast::error::RecvError::Lagged(n)) => {
+                            log::warn!("Status endpoint subscriber lagged by {} events", n);
+                            continue;
+                        }
+                        Err(broadcast::error::RecvError::Closed) => return None,
+                    },
+                    _ = tokio::time::sleep(KEEP_ALIVE_INTERVAL) => {
+                        let chunk: io::Result<bytes::Bytes> =
+                            Ok(bytes::Bytes::from_static(b": keep-alive\n\n"));
+                        return Some((chunk, (rx, last_seq)));
+                    }
                 }
             }
         });
 
-        res.body(Empty::new()).unwrap()
+        http::Response::builder()
+            .header(http::header::CONTENT_TYPE, "text/event-stream")
+            .body(hyper::Body::wrap_stream(replay.chain(live)))
+            .unwrap()
     }
 }
 
-impl<B> tower_service::Service<http::Request<B>> for &Service
+impl<B> tower_service::Service<http::Request<B>> for Arc<Service>
 where
     B: Body + Send + 'static,
     B::Data: Send,
     B::Error: Debug + Send,
 {
-    type Response = http::Response<Empty<&'static [u8]>>;
+    type Response = http::Response<hyper::Body>;
     type Error = std::convert::Infallible;
-    type Future = future::Ready<Result<Self::Response, Self::Error>>;
+    type Future =
+        Pin<Box<dyn future::Future<Output = Result<Self::Response, Self::Error>> + Send>>;
 
     fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
         Poll::Ready(Ok(()))
     }
 
     fn call(&mut self, req: http::Request<B>) -> Self::Future {
-        future::ready(Ok((*self).call(req)))
+        let this = Arc::clone(self);
+        Box::pin(async move { Ok(Service::call(&this, req).await) })
     }
 }
 
-const SIGNATURE_LEN: usize = <<Sha1 as FixedOutput>::OutputSize as Unsigned>::USIZE;
+/// Reads newline-delimited output from a hook's stdout/stderr pipe and republishes each line as
+/// an [`Event`], logging it at debug level too so it remains visible without a status subscriber.
+async fn forward_output<R>(
+    reader: R,
+    events: EventBus,
+    hook: Box<str>,
+    id: u64,
+    is_stderr: bool,
+) where
+    R: AsyncRead + Unpin,
+{
+    let mut lines = BufReader::new(reader).lines();
+    loop {
+        match lines.next_line().await {
+            Ok(Some(line)) => {
+                log::debug!("{}: {}", hook, line);
+                let event = if is_stderr {
+                    Event::Stderr {
+                        id,
+                        hook: hook.clone(),
+                        line: line.into(),
+                    }
+                } else {
+                    Event::Stdout {
+                        id,
+                        hook: hook.clone(),
+                        line: line.into(),
+                    }
+                };
+                events.publish(event);
+            }
+            Ok(None) => break,
+            Err(e) => {
+                log::error!("Failed to read output of `{}`: {:?}", hook, e);
+                break;
+            }
+        }
+    }
+}
+
+/// Formats an [`Event`] as a single `text/event-stream` message. `seq` is the event's unique,
+/// monotonically increasing position in the stream and becomes the SSE `id:` field; it is
+/// distinct from the hook-execution id embedded in `data:`, which several events can share.
+fn format_event(seq: u64, event: &Event) -> String {
+    use std::fmt::Write;
+
+    let mut buf = String::new();
+    match *event {
+        Event::Started { id, ref hook } => {
+            let _ = write!(buf, "id: {}\nevent: started\ndata: {} {}\n\n", seq, id, hook);
+        }
+        Event::Stdout {
+            id,
+            ref hook,
+            ref line,
+        } => {
+            let _ = write!(
+                buf,
+                "id: {}\nevent: stdout\ndata: {} {} {}\n\n",
+                seq, id, hook, line
+            );
+        }
+        Event::Stderr {
+            id,
+            ref hook,
+            ref line,
+        } => {
+            let _ = write!(
+                buf,
+                "id: {}\nevent: stderr\ndata: {} {} {}\n\n",
+                seq, id, hook, line
+            );
+        }
+        Event::Exited {
+            id,
+            ref hook,
+            code,
+            timed_out,
+        } => {
+            let code = code.map_or_else(|| "none".to_owned(), |c| c.to_string());
+            let _ = write!(
+                buf,
+                "id: {}\nevent: exited\ndata: {} {} {} {}\n\n",
+                seq, id, hook, code, timed_out
+            );
+        }
+    }
+    buf
+}
+
+const SHA1_LEN: usize = <<Sha1 as FixedOutput>::OutputSize as Unsigned>::USIZE;
+const SHA256_LEN: usize = <<Sha256 as FixedOutput>::OutputSize as Unsigned>::USIZE;
+
+/// The parsed value of an `X-Hub-Signature`/`X-Hub-Signature-256` header.
+enum Signature {
+    Sha1([u8; SHA1_LEN]),
+    Sha256([u8; SHA256_LEN]),
+}
+
+impl Signature {
+    fn algorithm(&self) -> Algorithm {
+        match *self {
+            Signature::Sha1(_) => Algorithm::Sha1,
+            Signature::Sha256(_) => Algorithm::Sha256,
+        }
+    }
+}
+
+/// An HMAC in progress, keyed with a hook's `secret` for the algorithm the sender signed with.
+enum Verifier {
+    Sha1(Hmac<Sha1>),
+    Sha256(Hmac<Sha256>),
+}
+
+impl Verifier {
+    fn update(&mut self, data: &[u8]) {
+        match self {
+            Verifier::Sha1(mac) => mac.update(data),
+            Verifier::Sha256(mac) => mac.update(data),
+        }
+    }
+
+    /// Consumes the MAC, comparing it against `signature` in constant time.
+    fn verify(self, signature: &Signature) -> bool {
+        match (self, signature) {
+            (Verifier::Sha1(mac), Signature::Sha1(code)) => mac.verify(code).is_ok(),
+            (Verifier::Sha256(mac), Signature::Sha256(code)) => mac.verify(code).is_ok(),
+            (Verifier::Sha1(_), Signature::Sha256(_))
+            | (Verifier::Sha256(_), Signature::Sha1(_)) => false,
+        }
+    }
+}
 
 enum SignatureParseError {
     Malformed,
     UnknownAlgorithm,
 }
 
-fn parse_signature_header(header: &[u8]) -> Result<[u8; SIGNATURE_LEN], SignatureParseError> {
+fn parse_signature_header(header: &[u8]) -> Result<Signature, SignatureParseError> {
     let pos = header.iter().position(|&b| b == b'=');
     let (method, signature_hex) = if let Some(i) = pos {
         let (method, hex) = header.split_at(i);
@@ -206,10 +718,16 @@ fn parse_signature_header(header: &[u8]) -> Result<[u8; SIGNATURE_LEN], Signatur
 
     match method {
         b"sha1" => {
-            let mut buf = [0u8; SIGNATURE_LEN];
+            let mut buf = [0u8; SHA1_LEN];
+            hex::decode_to_slice(signature_hex, &mut buf)
+                .map_err(|_| SignatureParseError::Malformed)?;
+            Ok(Signature::Sha1(buf))
+        }
+        b"sha256" => {
+            let mut buf = [0u8; SHA256_LEN];
             hex::decode_to_slice(signature_hex, &mut buf)
                 .map_err(|_| SignatureParseError::Malformed)?;
-            Ok(buf)
+            Ok(Signature::Sha256(buf))
         }
         _ => Err(SignatureParseError::UnknownAlgorithm),
     }
@@ -2,26 +2,65 @@ use std::io;
 use std::pin::Pin;
 use std::task::{Context, Poll};
 
+use futures_util::stream::{FuturesUnordered, StreamExt};
 use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio_rustls::server::TlsStream;
+use tokio_rustls::{Accept, TlsAcceptor};
 
 pub enum Listener {
     Tcp(tokio::net::TcpListener),
     #[cfg(unix)]
     Unix(tokio::net::UnixListener),
+    Tls {
+        inner: Box<Listener>,
+        acceptor: TlsAcceptor,
+        handshakes: FuturesUnordered<Accept<Stream>>,
+    },
 }
 
 pub enum Stream {
     Tcp(tokio::net::TcpStream),
     #[cfg(unix)]
     Unix(tokio::net::UnixStream),
+    Tls(Box<TlsStream<Stream>>),
 }
 
 impl Listener {
-    pub fn poll_accept(&self, cx: &mut Context<'_>) -> Poll<io::Result<Stream>> {
+    pub fn tls(inner: Listener, acceptor: TlsAcceptor) -> Self {
+        Listener::Tls {
+            inner: Box::new(inner),
+            acceptor,
+            handshakes: FuturesUnordered::new(),
+        }
+    }
+
+    pub fn poll_accept(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<Stream>> {
         match *self {
             Listener::Tcp(ref l) => l.poll_accept(cx).map_ok(|(sock, _)| Stream::Tcp(sock)),
             #[cfg(unix)]
             Listener::Unix(ref l) => l.poll_accept(cx).map_ok(|(sock, _)| Stream::Unix(sock)),
+            Listener::Tls {
+                ref mut inner,
+                ref acceptor,
+                ref mut handshakes,
+            } => loop {
+                if let Poll::Ready(Some(result)) = handshakes.poll_next_unpin(cx) {
+                    match result {
+                        Ok(stream) => return Poll::Ready(Ok(Stream::Tls(Box::new(stream)))),
+                        Err(e) => {
+                            log::warn!("TLS handshake failed: {:?}", e);
+                            continue;
+                        }
+                    }
+                }
+                match inner.poll_accept(cx) {
+                    Poll::Ready(Ok(sock)) => {
+                        handshakes.push(acceptor.accept(sock));
+                    }
+                    Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
+                    Poll::Pending => return Poll::Pending,
+                }
+            },
         }
     }
 }
@@ -36,6 +75,7 @@ impl AsyncRead for Stream {
             Stream::Tcp(ref mut s) => Pin::new(s).poll_read(cx, buf),
             #[cfg(unix)]
             Stream::Unix(ref mut s) => Pin::new(s).poll_read(cx, buf),
+            Stream::Tls(ref mut s) => Pin::new(s).poll_read(cx, buf),
         }
     }
 }
@@ -50,6 +90,7 @@ impl AsyncWrite for Stream {
             Stream::Tcp(ref mut s) => Pin::new(s).poll_write(cx, buf),
             #[cfg(unix)]
             Stream::Unix(ref mut s) => Pin::new(s).poll_write(cx, buf),
+            Stream::Tls(ref mut s) => Pin::new(s).poll_write(cx, buf),
         }
     }
 
@@ -58,6 +99,7 @@ impl AsyncWrite for Stream {
             Stream::Tcp(ref mut s) => Pin::new(s).poll_flush(cx),
             #[cfg(unix)]
             Stream::Unix(ref mut s) => Pin::new(s).poll_flush(cx),
+            Stream::Tls(ref mut s) => Pin::new(s).poll_flush(cx),
         }
     }
 
@@ -69,6 +111,7 @@ impl AsyncWrite for Stream {
             Stream::Tcp(ref mut s) => Pin::new(s).poll_shutdown(cx),
             #[cfg(unix)]
             Stream::Unix(ref mut s) => Pin::new(s).poll_shutdown(cx),
+            Stream::Tls(ref mut s) => Pin::new(s).poll_shutdown(cx),
         }
     }
 
@@ -81,6 +124,7 @@ impl AsyncWrite for Stream {
             Stream::Tcp(ref mut s) => Pin::new(s).poll_write_vectored(cx, bufs),
             #[cfg(unix)]
             Stream::Unix(ref mut s) => Pin::new(s).poll_write_vectored(cx, bufs),
+            Stream::Tls(ref mut s) => Pin::new(s).poll_write_vectored(cx, bufs),
         }
     }
 
@@ -89,6 +133,17 @@ impl AsyncWrite for Stream {
             Stream::Tcp(ref s) => s.is_write_vectored(),
             #[cfg(unix)]
             Stream::Unix(ref s) => s.is_write_vectored(),
+            Stream::Tls(ref s) => s.is_write_vectored(),
+        }
+    }
+}
+
+impl Stream {
+    /// Returns the protocol negotiated via ALPN during the TLS handshake, if any.
+    pub fn alpn_protocol(&self) -> Option<&[u8]> {
+        match *self {
+            Stream::Tls(ref s) => s.get_ref().1.alpn_protocol(),
+            _ => None,
         }
     }
 }